@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+
+use bevy::asset::{AssetEvent, HandleId};
+use bevy::prelude::*;
+use bevy::render::mesh::Mesh;
+
+use crate::primitives::*;
+use crate::raycast::{ray_triangle_intersection, RaycastAlgorithm};
+
+/// An axis-aligned bounding box, used to bound groups of triangles in a [Bvh].
+#[derive(Debug, Copy, Clone)]
+struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    fn empty() -> Self {
+        Aabb {
+            min: Vec3::splat(f32::MAX),
+            max: Vec3::splat(f32::MIN),
+        }
+    }
+
+    fn grow(&mut self, point: Vec3) {
+        self.min = self.min.min(point);
+        self.max = self.max.max(point);
+    }
+
+    fn union(&mut self, other: Aabb) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Branchless slab test. Returns the entry distance along the ray if it hits this box.
+    fn intersects_ray(&self, ray: &Ray3d) -> Option<f32> {
+        let inv_direction = ray.direction().recip();
+        let sign = [
+            (inv_direction.x < 0.0) as usize,
+            (inv_direction.y < 0.0) as usize,
+            (inv_direction.z < 0.0) as usize,
+        ];
+        let bounds = [self.min, self.max];
+        let origin = ray.origin();
+
+        let mut tmin = (bounds[sign[0]].x - origin.x) * inv_direction.x;
+        let mut tmax = (bounds[1 - sign[0]].x - origin.x) * inv_direction.x;
+        let tymin = (bounds[sign[1]].y - origin.y) * inv_direction.y;
+        let tymax = (bounds[1 - sign[1]].y - origin.y) * inv_direction.y;
+
+        if tmin > tymax || tymin > tmax {
+            return None;
+        }
+        if tymin > tmin {
+            tmin = tymin;
+        }
+        if tymax < tmax {
+            tmax = tymax;
+        }
+
+        let tzmin = (bounds[sign[2]].z - origin.z) * inv_direction.z;
+        let tzmax = (bounds[1 - sign[2]].z - origin.z) * inv_direction.z;
+
+        if tmin > tzmax || tzmin > tmax {
+            return None;
+        }
+        if tzmin > tmin {
+            tmin = tzmin;
+        }
+        if tzmax < tmax {
+            tmax = tzmax;
+        }
+
+        if tmax < 0.0 {
+            return None;
+        }
+
+        Some(tmin.max(0.0))
+    }
+}
+
+enum BvhNode {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } => *bounds,
+            BvhNode::Interior { bounds, .. } => *bounds,
+        }
+    }
+}
+
+/// Maximum number of triangles kept in a single leaf before a node is split further.
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// A bounding-volume hierarchy over a fixed set of triangles, used to prune the triangles a
+/// raycast needs to test against instead of brute-forcing every one of them.
+///
+/// Build once with [Bvh::from_triangles] and reuse it for as many casts as needed with
+/// [Bvh::cast].
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Each triangle, paired with its index in the slice originally passed to
+    /// [Bvh::from_triangles]. Building the hierarchy reorders this array, so the original index
+    /// has to travel alongside each triangle instead of being recoverable from its position.
+    triangles: Vec<(Triangle, usize)>,
+    root: usize,
+}
+
+impl Bvh {
+    /// Build a BVH over the given triangles by recursively splitting along the longest axis of
+    /// the containing AABB, at the median triangle centroid.
+    pub fn from_triangles(triangles: &[Triangle]) -> Self {
+        let mut triangles: Vec<(Triangle, usize)> =
+            triangles.iter().copied().zip(0..).collect();
+        let mut nodes = Vec::new();
+        let root = if triangles.is_empty() {
+            nodes.push(BvhNode::Leaf {
+                bounds: Aabb::empty(),
+                start: 0,
+                end: 0,
+            });
+            0
+        } else {
+            let len = triangles.len();
+            Self::build(&mut triangles, &mut nodes, 0, len)
+        };
+        Bvh {
+            nodes,
+            triangles,
+            root,
+        }
+    }
+
+    fn build(
+        triangles: &mut [(Triangle, usize)],
+        nodes: &mut Vec<BvhNode>,
+        start: usize,
+        end: usize,
+    ) -> usize {
+        let mut bounds = Aabb::empty();
+        for (triangle, _) in &triangles[start..end] {
+            bounds.grow(triangle.v0);
+            bounds.grow(triangle.v1);
+            bounds.grow(triangle.v2);
+        }
+
+        if end - start <= MAX_LEAF_TRIANGLES {
+            nodes.push(BvhNode::Leaf { bounds, start, end });
+            return nodes.len() - 1;
+        }
+
+        let axis = bounds.longest_axis();
+        let triangle_centroid = |triangle: &Triangle| -> f32 {
+            let centroid = (triangle.v0 + triangle.v1 + triangle.v2) / 3.0;
+            match axis {
+                0 => centroid.x,
+                1 => centroid.y,
+                _ => centroid.z,
+            }
+        };
+        let mid = start + (end - start) / 2;
+        triangles[start..end].select_nth_unstable_by(mid - start, |(a, _), (b, _)| {
+            triangle_centroid(a)
+                .partial_cmp(&triangle_centroid(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let left = Self::build(triangles, nodes, start, mid);
+        let right = Self::build(triangles, nodes, mid, end);
+        // The children's own bounds are at least as tight as the pre-split scan above, so union
+        // them together instead of reusing `bounds`.
+        let mut interior_bounds = nodes[left].bounds();
+        interior_bounds.union(nodes[right].bounds());
+        nodes.push(BvhNode::Interior {
+            bounds: interior_bounds,
+            left,
+            right,
+        });
+        nodes.len() - 1
+    }
+
+    /// Cast a ray against the hierarchy, descending only into boxes the ray passes through, and
+    /// return the nearest triangle intersection, if any. Uses the default [RaycastAlgorithm] and
+    /// no distance cutoff; see [Bvh::cast_with] to customize either.
+    pub fn cast(&self, ray: &Ray3d) -> Option<Intersection> {
+        self.cast_with(ray, RaycastAlgorithm::default(), f32::MAX)
+    }
+
+    /// Cast a ray against the hierarchy using the given algorithm and maximum time-of-impact.
+    ///
+    /// Traversal keeps a running closest-hit distance `best`, descends into whichever child the
+    /// ray enters first, and prunes any node (or triangle) whose entry distance exceeds `best` -
+    /// so once a close hit is found, most of the remaining tree is never visited.
+    pub fn cast_with(
+        &self,
+        ray: &Ray3d,
+        algorithm: RaycastAlgorithm,
+        max_toi: f32,
+    ) -> Option<Intersection> {
+        let mut best = max_toi;
+        let mut best_hit = None;
+        if matches!(self.nodes[self.root].bounds().intersects_ray(ray), Some(entry) if entry <= best)
+        {
+            self.cast_node(self.root, ray, algorithm, &mut best, &mut best_hit);
+        }
+        best_hit
+    }
+
+    fn cast_node(
+        &self,
+        node_index: usize,
+        ray: &Ray3d,
+        algorithm: RaycastAlgorithm,
+        best: &mut f32,
+        best_hit: &mut Option<Intersection>,
+    ) {
+        match &self.nodes[node_index] {
+            BvhNode::Leaf { start, end, .. } => {
+                for (triangle, index) in &self.triangles[*start..*end] {
+                    if let Some(hit) = ray_triangle_intersection(ray, triangle, algorithm, *best) {
+                        if hit.distance() < *best {
+                            *best = hit.distance();
+                            *best_hit = Some(Intersection::new(
+                                hit.position(),
+                                hit.normal(),
+                                hit.distance(),
+                                hit.barycentric(),
+                                hit.front_face(),
+                                hit.world_triangle(),
+                                Some(*index),
+                            ));
+                        }
+                    }
+                }
+            }
+            BvhNode::Interior { left, right, .. } => {
+                let left_entry = self.nodes[*left].bounds().intersects_ray(ray);
+                let right_entry = self.nodes[*right].bounds().intersects_ray(ray);
+                // Visit whichever child the ray enters first - a hit found there can shrink
+                // `best` enough to prune the other child before it's even descended into.
+                let (near, near_entry, far, far_entry) =
+                    if right_entry.unwrap_or(f32::MAX) < left_entry.unwrap_or(f32::MAX) {
+                        (*right, right_entry, *left, left_entry)
+                    } else {
+                        (*left, left_entry, *right, right_entry)
+                    };
+                if matches!(near_entry, Some(entry) if entry <= *best) {
+                    self.cast_node(near, ray, algorithm, best, best_hit);
+                }
+                if matches!(far_entry, Some(entry) if entry <= *best) {
+                    self.cast_node(far, ray, algorithm, best, best_hit);
+                }
+            }
+        }
+    }
+}
+
+/// Caches a [Bvh] per mesh asset, keyed by [HandleId], so it's only built once no matter how many
+/// times that mesh is ray cast against. Meant to be stored as a `Local` resource by systems or
+/// system params that repeatedly cast against the same meshes.
+#[derive(Default)]
+pub(crate) struct MeshBvhCache {
+    bvhs: HashMap<HandleId, Bvh>,
+}
+
+impl MeshBvhCache {
+    /// Below this many triangles, building and maintaining a BVH costs more than it saves; callers
+    /// should test triangles directly instead of consulting this cache.
+    pub const MIN_TRIANGLES: usize = 64;
+
+    /// Returns the cached [Bvh] for `handle_id`, building it from `triangles` the first time it's
+    /// requested.
+    pub fn get_or_build(
+        &mut self,
+        handle_id: HandleId,
+        triangles: impl FnOnce() -> Vec<Triangle>,
+    ) -> &Bvh {
+        self.bvhs
+            .entry(handle_id)
+            .or_insert_with(|| Bvh::from_triangles(&triangles()))
+    }
+
+    /// Drops the cached BVH for any mesh reported as modified or removed, so the next
+    /// [get_or_build](MeshBvhCache::get_or_build) call rebuilds it from the mesh's current
+    /// geometry instead of returning a stale tree.
+    pub fn handle_asset_events<'a>(&mut self, events: impl Iterator<Item = &'a AssetEvent<Mesh>>) {
+        for event in events {
+            match event {
+                AssetEvent::Modified { handle } | AssetEvent::Removed { handle } => {
+                    self.bvhs.remove(&handle.id);
+                }
+                AssetEvent::Created { .. } => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(offset: f32) -> Triangle {
+        Triangle::from([
+            Vec3::new(offset - 1.0, -1.0, 2.0),
+            Vec3::new(offset - 1.0, 2.0, -1.0),
+            Vec3::new(offset - 1.0, -1.0, -1.0),
+        ])
+    }
+
+    #[test]
+    fn casts_against_nearest_triangle() {
+        let triangles = vec![triangle(0.0), triangle(5.0), triangle(10.0)];
+        let bvh = Bvh::from_triangles(&triangles);
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let hit = bvh.cast(&ray).expect("ray should hit the nearest triangle");
+        // The first triangle sits at x = -1, behind the ray's origin, so the nearest hit is the
+        // second one at x = 4.
+        assert_eq!(hit.position(), Vec3::new(4.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn misses_return_none() {
+        let triangles = vec![triangle(0.0)];
+        let bvh = Bvh::from_triangles(&triangles);
+        let ray = Ray3d::new(Vec3::new(0.0, 10.0, 0.0), Vec3::X);
+        assert!(bvh.cast(&ray).is_none());
+    }
+
+    #[test]
+    fn reports_original_triangle_index() {
+        let triangles = vec![triangle(0.0), triangle(5.0), triangle(10.0)];
+        let bvh = Bvh::from_triangles(&triangles);
+        let ray = Ray3d::new(Vec3::new(2.0, 0.0, 0.0), Vec3::X);
+        let hit = bvh.cast(&ray).expect("ray should hit the second triangle");
+        assert_eq!(hit.triangle_index(), Some(1));
+    }
+
+    #[test]
+    fn rebuilds_after_modified_event() {
+        let handle_id = HandleId::random::<Mesh>();
+        let mut cache = MeshBvhCache::default();
+        let ray = Ray3d::new(Vec3::new(2.0, 0.0, 0.0), Vec3::X);
+
+        // Build against geometry with nothing in front of the ray...
+        cache.get_or_build(handle_id, || vec![triangle(0.0)]);
+        let stale = cache.get_or_build(handle_id, || vec![triangle(5.0)]);
+        // ...and confirm the cache kept serving that stale tree instead of rebuilding.
+        assert!(stale.cast(&ray).is_none());
+
+        cache.handle_asset_events(std::iter::once(&AssetEvent::Modified {
+            handle: Handle::weak(handle_id),
+        }));
+
+        let rebuilt = cache.get_or_build(handle_id, || vec![triangle(5.0)]);
+        let hit = rebuilt
+            .cast(&ray)
+            .expect("rebuilt tree should reflect the mesh's current geometry");
+        assert_eq!(hit.position(), Vec3::new(4.0, 0.0, 0.0));
+    }
+}
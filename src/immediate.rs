@@ -0,0 +1,91 @@
+use bevy::{
+    asset::AssetEvent,
+    prelude::*,
+    render::mesh::Mesh,
+};
+
+use crate::bvh::MeshBvhCache;
+use crate::{cast_ray_against_mesh, BoundVol, Intersection, Ray3d, RayCastSettings};
+
+/// An immediate-mode ray cast: borrows the mesh assets and the mesh/transform/visibility queries
+/// needed to fire a ray cast on the spot, from any system, at any point in the schedule.
+///
+/// Unlike [RayCastSource](crate::RayCastSource), this isn't tied to a marker type `T` or to
+/// waiting for `update_raycast` to run in `PostUpdate` - it's meant for one-off queries like AI
+/// line-of-sight checks, projectile hit tests, or editor gizmos.
+#[derive(SystemParam)]
+pub struct MeshRayCast<'w, 's> {
+    meshes: Res<'w, Assets<Mesh>>,
+    #[allow(clippy::type_complexity)]
+    mesh_query: Query<
+        'w,
+        's,
+        (
+            &'static Handle<Mesh>,
+            &'static GlobalTransform,
+            &'static Visible,
+            Option<&'static BoundVol>,
+            Entity,
+        ),
+    >,
+    bvh_cache: Local<'s, MeshBvhCache>,
+    mesh_asset_events: EventReader<'w, 's, AssetEvent<Mesh>>,
+}
+
+impl<'w, 's> MeshRayCast<'w, 's> {
+    /// Cast `ray` right now against every mesh that passes `settings.filter` and is visible (or
+    /// regardless of visibility, if `settings.ignore_visibility` is set), returning every
+    /// hit sorted by ascending distance. If `settings.early_exit_test` accepts a hit, the search
+    /// stops there and the hits found so far are returned unsorted.
+    pub fn cast_ray(&mut self, ray: Ray3d, settings: &RayCastSettings) -> Vec<(Entity, Intersection)> {
+        self.bvh_cache
+            .handle_asset_events(self.mesh_asset_events.iter());
+        let mut picks = Vec::new();
+        for (mesh_handle, transform, visible, bound_vol, entity) in self.mesh_query.iter() {
+            if !(visible.is_visible || settings.ignore_visibility) {
+                continue;
+            }
+            if !(settings.filter)(entity) {
+                continue;
+            }
+            // Check the entity's bounding sphere (if it has one) before testing the full mesh -
+            // this is the same broad-phase `update_raycast` uses to skip most of the scene.
+            if let Some(bound_vol) = bound_vol {
+                if let Some(sphere) = &bound_vol.sphere {
+                    let scaled_radius: f32 = 1.01 * sphere.radius() * transform.scale.max_element();
+                    let translated_origin =
+                        sphere.origin() * transform.scale + transform.translation;
+                    let det = (ray.direction().dot(ray.origin() - translated_origin)).powi(2)
+                        - (Vec3::length_squared(ray.origin() - translated_origin)
+                            - scaled_radius.powi(2));
+                    if det < 0.0 {
+                        continue;
+                    }
+                }
+            }
+            let intersection = self.meshes.get(mesh_handle).and_then(|mesh| {
+                let mesh_to_world = transform.compute_matrix();
+                cast_ray_against_mesh(
+                    mesh_handle,
+                    mesh,
+                    &mesh_to_world,
+                    &ray,
+                    settings,
+                    &mut self.bvh_cache,
+                )
+            });
+            if let Some(intersection) = intersection {
+                picks.push((entity, intersection));
+                if (settings.early_exit_test)(entity) {
+                    return picks;
+                }
+            }
+        }
+        picks.sort_by(|a, b| {
+            a.1.distance()
+                .partial_cmp(&b.1.distance())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        picks
+    }
+}
@@ -1,6 +1,7 @@
 use std::marker::PhantomData;
 
 use bevy::{
+    asset::AssetEvent,
     prelude::*,
     render::{
         camera::Camera,
@@ -10,16 +11,23 @@ use bevy::{
 };
 
 pub use crate::bounding::{BoundingSphere, BoundVol, update_bound_sphere};
+pub use crate::bvh::Bvh;
+use crate::bvh::MeshBvhCache;
 #[cfg(feature = "debug")]
 pub use crate::debug::*;
+pub use crate::immediate::MeshRayCast;
 pub use crate::primitives::*;
-use crate::raycast::*;
+pub use crate::raycast::*;
+pub use crate::shapes::*;
 
 mod bounding;
+mod bvh;
 #[cfg(feature = "debug")]
 mod debug;
+mod immediate;
 mod primitives;
 mod raycast;
+mod shapes;
 
 pub struct DefaultRaycastingPlugin<T: 'static + Send + Sync>(pub PhantomData<T>);
 
@@ -130,11 +138,45 @@ impl<T> Default for RayCastMesh<T> {
     }
 }
 
+/// Settings that control how a single ray cast is performed, independent of how the ray itself was
+/// generated.
+#[derive(Debug, Clone, Copy)]
+pub struct RayCastSettings {
+    /// Intersections further than this distance along the ray are ignored.
+    pub max_toi: f32,
+    /// Whether triangles hit from behind (where the ray direction and the triangle's normal point
+    /// the same way) are recorded or discarded.
+    pub backface_culling: Backfaces,
+    /// Only entities for which this returns `true` are considered. Defaults to accepting every
+    /// entity.
+    pub filter: fn(Entity) -> bool,
+    /// If this returns `true` for a hit entity, the search stops there: the remaining entities
+    /// aren't tested and the hits found so far aren't sorted. Defaults to never exiting early, so
+    /// every hit along the ray is found and sorted by distance.
+    pub early_exit_test: fn(Entity) -> bool,
+    /// If `true`, entities are considered regardless of their `Visible` component. Defaults to
+    /// `false`, i.e. only visible entities are hit.
+    pub ignore_visibility: bool,
+}
+
+impl Default for RayCastSettings {
+    fn default() -> Self {
+        RayCastSettings {
+            max_toi: f32::MAX,
+            backface_culling: Backfaces::Cull,
+            filter: |_| true,
+            early_exit_test: |_| false,
+            ignore_visibility: false,
+        }
+    }
+}
+
 /// The `RayCastSource` component is used to generate rays with the specified `cast_method`. A `ray`
 /// is generated when the RayCastSource is initialized, either by waiting for update_raycast system
 /// to process the ray, or by using a `with_ray` function.
 pub struct RayCastSource<T> {
     pub cast_method: RayCastMethod,
+    pub settings: RayCastSettings,
     ray: Option<Ray3d>,
     intersections: Vec<(Entity, Intersection)>,
     _marker: PhantomData<T>,
@@ -144,6 +186,7 @@ impl<T> Default for RayCastSource<T> {
     fn default() -> Self {
         RayCastSource {
             cast_method: RayCastMethod::Screenspace(Vec2::ZERO),
+            settings: RayCastSettings::default(),
             ray: None,
             intersections: Vec::new(),
             _marker: PhantomData::default(),
@@ -167,6 +210,7 @@ impl<T> RayCastSource<T> {
     ) -> Self {
         RayCastSource {
             cast_method: RayCastMethod::Screenspace(cursor_pos_screen),
+            settings: self.settings,
             ray: Ray3d::from_screenspace(cursor_pos_screen, windows, camera, camera_transform),
             intersections: self.intersections.clone(),
             _marker: self._marker,
@@ -176,6 +220,7 @@ impl<T> RayCastSource<T> {
     pub fn with_ray_transform(&self, transform: Mat4) -> Self {
         RayCastSource {
             cast_method: RayCastMethod::Transform,
+            settings: self.settings,
             ray: Some(Ray3d::from_transform(transform)),
             intersections: self.intersections.clone(),
             _marker: self._marker,
@@ -239,15 +284,30 @@ impl<T> RayCastSource<T> {
                     let point_to_point = plane_origin - ray.origin();
                     let intersect_dist = plane_normal.dot(point_to_point) / denominator;
                     let intersect_position = ray.direction() * intersect_dist + ray.origin();
+                    let front_face = denominator < 0.0;
                     Some(Intersection::new(
-                        Ray3d::new(intersect_position, plane_normal),
+                        intersect_position,
+                        plane_normal,
                         intersect_dist,
+                        Vec3::ZERO,
+                        front_face,
+                        None,
                         None,
                     ))
                 } else {
                     None
                 }
             }
+            Primitive3d::Sphere { center, radius } => {
+                ray_sphere_intersection(&ray, &Sphere { center, radius })
+            }
+            Primitive3d::Aabb { min, max } => ray_aabb_intersection(&ray, &Aabb { min, max }),
+            Primitive3d::Triangle { v0, v1, v2 } => ray_triangle_intersection(
+                &ray,
+                &Triangle::from([v0, v1, v2]),
+                RaycastAlgorithm::MollerTrumbore(self.settings.backface_culling),
+                self.settings.max_toi,
+            ),
         }
     }
 
@@ -335,6 +395,8 @@ pub fn update_raycast<T: 'static + Send + Sync>(
     // Resources
     state: Res<PluginState<T>>,
     meshes: Res<Assets<Mesh>>,
+    mut bvh_cache: Local<MeshBvhCache>,
+    mut mesh_asset_events: EventReader<AssetEvent<Mesh>>,
     // Queries
     mut pick_source_query: Query<&mut RayCastSource<T>>,
     culling_query: Query<
@@ -346,8 +408,10 @@ pub fn update_raycast<T: 'static + Send + Sync>(
     if !state.enabled {
         return;
     }
+    bvh_cache.handle_asset_events(mesh_asset_events.iter());
     for mut pick_source in pick_source_query.iter_mut() {
         if let Some(ray) = pick_source.ray {
+            let settings = pick_source.settings;
             pick_source.intersections.clear();
             // Create spans for tracing
             let ray_cull = info_span!("ray culling");
@@ -360,7 +424,7 @@ pub fn update_raycast<T: 'static + Send + Sync>(
                 culling_query
                     .iter()
                     .map(|(visibility, bound_vol, transform, entity)| {
-                        let visible = visibility.is_visible;
+                        let visible = visibility.is_visible || settings.ignore_visibility;
                         let bound_hit = if let Some(bound_vol) = bound_vol {
                             if let Some(sphere) = &bound_vol.sphere {
                                 let scaled_radius: f32 =
@@ -378,7 +442,7 @@ pub fn update_raycast<T: 'static + Send + Sync>(
                         } else {
                             true // This entity has no bounding volume
                         };
-                        if visible && bound_hit {
+                        if visible && bound_hit && (settings.filter)(entity) {
                             Some(entity)
                         } else {
                             None
@@ -388,124 +452,285 @@ pub fn update_raycast<T: 'static + Send + Sync>(
                     .collect()
             };
 
-            let mut picks = mesh_query
-                .iter()
-                .filter(|(_mesh_handle, _transform, entity)| culled_list.contains(&entity))
-                .filter_map(|(mesh_handle, transform, entity)| {
-                    let _raycast_guard = raycast.enter();
-                    // Use the mesh handle to get a reference to a mesh asset
-                    if let Some(mesh) = meshes.get(mesh_handle) {
-                        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
-                            error!("bevy_mod_picking only supports TriangleList mesh topology");
-                        }
-                        // Get the vertex positions from the mesh reference resolved from the mesh handle
-                        let vertex_positions: &Vec<[f32; 3]> =
-                            match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
-                                None => panic!("Mesh does not contain vertex positions"),
-                                Some(vertex_values) => match &vertex_values {
-                                    VertexAttributeValues::Float32x3(positions) => positions,
-                                    _ => panic!("Unexpected vertex types in ATTRIBUTE_POSITION"),
-                                },
-                            };
-                        if let Some(indices) = &mesh.indices() {
-                            // Iterate over the list of pick rays that belong to the same group as this mesh
-                            let mesh_to_world = transform.compute_matrix();
-                            let new_intersection = match indices {
-                                Indices::U16(vector) => ray_mesh_intersection(
-                                    &mesh_to_world,
-                                    vertex_positions,
-                                    &ray,
-                                    &vector.iter().map(|x| *x as u32).collect(),
-                                ),
-                                Indices::U32(vector) => ray_mesh_intersection(
-                                    &mesh_to_world,
-                                    vertex_positions,
-                                    &ray,
-                                    vector,
-                                ),
-                            };
-                            //pickable.intersection = new_intersection;
-                            if let Some(new_intersection) = new_intersection {
-                                Some((entity, new_intersection))
-                            } else {
-                                None
-                            }
-                        } else {
-                            // If we get here the mesh doesn't have an index list!
-                            panic!(
-                                "No index matrix found in mesh {:?}\n{:?}",
-                                mesh_handle, mesh
-                            );
-                        }
-                    } else {
-                        None
+            // Walk the culled list, stopping as soon as `early_exit_test` accepts a hit - this
+            // skips both the remaining triangle tests and the final sort, since the caller only
+            // wants the first qualifying hit.
+            let mut picks = Vec::new();
+            let mut exited_early = false;
+            for (mesh_handle, transform, entity) in mesh_query.iter() {
+                if !culled_list.contains(&entity) {
+                    continue;
+                }
+                let _raycast_guard = raycast.enter();
+                let intersection = meshes.get(mesh_handle).and_then(|mesh| {
+                    let mesh_to_world = transform.compute_matrix();
+                    cast_ray_against_mesh(
+                        mesh_handle,
+                        mesh,
+                        &mesh_to_world,
+                        &ray,
+                        &settings,
+                        &mut bvh_cache,
+                    )
+                });
+                if let Some(intersection) = intersection {
+                    picks.push((entity, intersection));
+                    if (settings.early_exit_test)(entity) {
+                        exited_early = true;
+                        break;
                     }
-                })
-                .collect::<Vec<(Entity, Intersection)>>();
-            picks.sort_by(|a, b| {
-                a.1.distance()
-                    .partial_cmp(&b.1.distance())
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            });
+                }
+            }
+            if !exited_early {
+                picks.sort_by(|a, b| {
+                    a.1.distance()
+                        .partial_cmp(&b.1.distance())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+            }
 
             pick_source.intersections = picks;
         }
     }
 }
 
-/// Checks if a ray intersects a mesh, and returns the nearest intersection if one exists.
+/// Resolves a mesh's vertex/index buffers and casts `ray` against it, returning the nearest
+/// intersection. Used by both the [RayCastSource] update loop and the immediate-mode
+/// [MeshRayCast] system param so the mesh-resolving logic only lives in one place.
+fn cast_ray_against_mesh(
+    mesh_handle: &Handle<Mesh>,
+    mesh: &Mesh,
+    mesh_to_world: &Mat4,
+    ray: &Ray3d,
+    settings: &RayCastSettings,
+    bvh_cache: &mut MeshBvhCache,
+) -> Option<Intersection> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        error!("bevy_mod_picking only supports TriangleList mesh topology");
+    }
+    // Get the vertex positions from the mesh reference resolved from the mesh handle
+    let vertex_positions: &Vec<[f32; 3]> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        None => panic!("Mesh does not contain vertex positions"),
+        Some(vertex_values) => match &vertex_values {
+            VertexAttributeValues::Float32x3(positions) => positions,
+            _ => panic!("Unexpected vertex types in ATTRIBUTE_POSITION"),
+        },
+    };
+    match &mesh.indices() {
+        Some(Indices::U16(vector)) => ray_mesh_intersection(
+            mesh_handle,
+            mesh_to_world,
+            vertex_positions,
+            ray,
+            &vector.iter().map(|x| *x as u32).collect(),
+            settings,
+            bvh_cache,
+        ),
+        Some(Indices::U32(vector)) => ray_mesh_intersection(
+            mesh_handle,
+            mesh_to_world,
+            vertex_positions,
+            ray,
+            vector,
+            settings,
+            bvh_cache,
+        ),
+        // If we get here the mesh doesn't have an index list!
+        None => panic!("No index matrix found in mesh {:?}\n{:?}", mesh_handle, mesh),
+    }
+}
+
+/// Checks if a ray intersects a mesh, and returns the nearest intersection if one exists. Meshes
+/// with at least [MeshBvhCache::MIN_TRIANGLES] triangles are ray cast via a cached [Bvh] built
+/// over the mesh's local-space triangles; smaller meshes are tested directly, since building a
+/// BVH for them isn't worth it.
 #[allow(clippy::ptr_arg)]
 fn ray_mesh_intersection(
+    mesh_handle: &Handle<Mesh>,
+    mesh_to_world: &Mat4,
+    vertex_positions: &[[f32; 3]],
+    pick_ray: &Ray3d,
+    indices: &Vec<u32>,
+    settings: &RayCastSettings,
+    bvh_cache: &mut MeshBvhCache,
+) -> Option<Intersection> {
+    if indices.len() % 3 != 0 {
+        return None;
+    }
+
+    if indices.len() / 3 < MeshBvhCache::MIN_TRIANGLES {
+        return ray_mesh_intersection_linear(mesh_to_world, vertex_positions, pick_ray, indices, settings);
+    }
+
+    let bvh = bvh_cache.get_or_build(mesh_handle.id, || {
+        indices
+            .chunks(3)
+            .map(|index| {
+                Triangle::from([
+                    Vec3::from(vertex_positions[index[0] as usize]),
+                    Vec3::from(vertex_positions[index[1] as usize]),
+                    Vec3::from(vertex_positions[index[2] as usize]),
+                ])
+            })
+            .collect::<Vec<Triangle>>()
+    });
+
+    let world_to_mesh = mesh_to_world.inverse();
+    let local_ray = Ray3d::new(
+        world_to_mesh.transform_point3(pick_ray.origin()),
+        world_to_mesh.transform_vector3(pick_ray.direction()),
+    );
+    let local_hit = bvh.cast_with(
+        &local_ray,
+        RaycastAlgorithm::MollerTrumbore(settings.backface_culling),
+        settings.max_toi,
+    )?;
+
+    let position = mesh_to_world.transform_point3(local_hit.position());
+    let triangle = local_hit.world_triangle().map(|triangle| Triangle {
+        v0: mesh_to_world.transform_point3(triangle.v0),
+        v1: mesh_to_world.transform_point3(triangle.v1),
+        v2: mesh_to_world.transform_point3(triangle.v2),
+    });
+    // Recompute the normal from the world-space triangle's edges instead of transforming the
+    // local-space normal by `mesh_to_world` - under non-uniform scale, transforming a normal by
+    // the model matrix itself (rather than the inverse-transpose of its linear part) skews it.
+    let normal = triangle
+        .map(|triangle| (triangle.v1 - triangle.v0).cross(triangle.v2 - triangle.v0))
+        .unwrap_or_else(|| mesh_to_world.transform_vector3(local_hit.normal()))
+        .normalize();
+    let distance = (position - pick_ray.origin()).length() / pick_ray.direction().length();
+    let front_face = pick_ray.direction().dot(normal) < 0.0;
+    Some(Intersection::new(
+        position,
+        normal,
+        distance,
+        local_hit.barycentric(),
+        front_face,
+        triangle,
+        local_hit.triangle_index(),
+    ))
+}
+
+/// Brute-force fallback for [ray_mesh_intersection]: tests every triangle directly, without
+/// consulting a [Bvh].
+fn ray_mesh_intersection_linear(
     mesh_to_world: &Mat4,
     vertex_positions: &[[f32; 3]],
     pick_ray: &Ray3d,
     indices: &Vec<u32>,
+    settings: &RayCastSettings,
 ) -> Option<Intersection> {
     // The ray cast can hit the same mesh many times, so we need to track which hit is
     // closest to the camera, and record that.
     let mut min_pick_distance_squared = f32::MAX;
     let mut pick_intersection = None;
 
-    // Make sure this chunk has 3 vertices to avoid a panic.
-    if indices.len() % 3 == 0 {
-        // Now that we're in the vector of vertex indices, we want to look at the vertex
-        // positions for each triangle, so we'll take indices in chunks of three, where each
-        // chunk of three indices are references to the three vertices of a triangle.
-        for index in indices.chunks(3) {
-            // Construct a triangle in world space using the mesh data
-            let mut world_vertices: [Vec3; 3] = [Vec3::ZERO, Vec3::ZERO, Vec3::ZERO];
-            for i in 0..3 {
-                let vertex_index = index[i] as usize;
-                world_vertices[i] =
-                    mesh_to_world.project_point3(Vec3::from(vertex_positions[vertex_index]));
-            }
-            // If all vertices in the triangle are further away than the nearest hit, skip
-            if world_vertices
-                .iter()
-                .map(|vert| (*vert - pick_ray.origin()).length_squared().abs())
-                .fold(f32::INFINITY, |a, b| a.min(b))
-                > min_pick_distance_squared
-            {
-                continue;
-            }
-            let world_triangle = Triangle::from(world_vertices);
-            // Run the raycast on the ray and triangle
-            if let Some(intersection) =
-            ray_triangle_intersection(pick_ray, &world_triangle, RaycastAlgorithm::default())
-            {
-                let distance: f32 = (intersection.origin() - pick_ray.origin())
-                    .length_squared()
-                    .abs();
-                if distance < min_pick_distance_squared {
-                    min_pick_distance_squared = distance;
-                    pick_intersection = Some(Intersection::new(
-                        intersection,
-                        distance,
-                        Some(world_triangle),
-                    ));
-                }
+    // Now that we're in the vector of vertex indices, we want to look at the vertex
+    // positions for each triangle, so we'll take indices in chunks of three, where each
+    // chunk of three indices are references to the three vertices of a triangle.
+    for (triangle_index, index) in indices.chunks(3).enumerate() {
+        // Construct a triangle in world space using the mesh data
+        let mut world_vertices: [Vec3; 3] = [Vec3::ZERO, Vec3::ZERO, Vec3::ZERO];
+        for i in 0..3 {
+            let vertex_index = index[i] as usize;
+            world_vertices[i] =
+                mesh_to_world.project_point3(Vec3::from(vertex_positions[vertex_index]));
+        }
+        // If all vertices in the triangle are further away than the nearest hit, skip
+        if world_vertices
+            .iter()
+            .map(|vert| (*vert - pick_ray.origin()).length_squared().abs())
+            .fold(f32::INFINITY, |a, b| a.min(b))
+            > min_pick_distance_squared
+        {
+            continue;
+        }
+        let world_triangle = Triangle::from(world_vertices);
+        // Run the raycast on the ray and triangle
+        if let Some(intersection) = ray_triangle_intersection(
+            pick_ray,
+            &world_triangle,
+            RaycastAlgorithm::MollerTrumbore(settings.backface_culling),
+            settings.max_toi,
+        ) {
+            let distance = (intersection.position() - pick_ray.origin())
+                .length_squared()
+                .abs();
+            if distance < min_pick_distance_squared {
+                min_pick_distance_squared = distance;
+                pick_intersection = Some(Intersection::new(
+                    intersection.position(),
+                    intersection.normal(),
+                    intersection.distance(),
+                    intersection.barycentric(),
+                    intersection.front_face(),
+                    intersection.world_triangle(),
+                    Some(triangle_index),
+                ));
             }
         }
     }
     pick_intersection
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::HandleId;
+
+    use super::*;
+
+    #[test]
+    fn bvh_path_normal_respects_non_uniform_scale() {
+        let local_triangle = Triangle::from([
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 1.0),
+        ]);
+        let mut vertex_positions: Vec<[f32; 3]> = vec![
+            [local_triangle.v0.x, local_triangle.v0.y, local_triangle.v0.z],
+            [local_triangle.v1.x, local_triangle.v1.y, local_triangle.v1.z],
+            [local_triangle.v2.x, local_triangle.v2.y, local_triangle.v2.z],
+        ];
+        let mut indices: Vec<u32> = vec![0, 1, 2];
+        // Pad out to the BVH threshold with triangles far from the ray, so this exercises the
+        // cached-BVH path rather than the brute-force linear fallback.
+        for i in 0..(MeshBvhCache::MIN_TRIANGLES - 1) {
+            let base = vertex_positions.len() as u32;
+            let offset = 100.0 + i as f32;
+            vertex_positions.push([offset, offset, offset]);
+            vertex_positions.push([offset + 1.0, offset, offset]);
+            vertex_positions.push([offset, offset + 1.0, offset]);
+            indices.extend_from_slice(&[base, base + 1, base + 2]);
+        }
+
+        // Non-uniform scale: transforming the local-space normal by this matrix directly (instead
+        // of recomputing it from the transformed triangle) would skew its direction.
+        let mesh_to_world = Mat4::from_scale(Vec3::new(1.0, 1.0, 2.0));
+        let world_triangle = Triangle::from([
+            mesh_to_world.transform_point3(local_triangle.v0),
+            mesh_to_world.transform_point3(local_triangle.v1),
+            mesh_to_world.transform_point3(local_triangle.v2),
+        ]);
+        let expected_normal = (world_triangle.v1 - world_triangle.v0)
+            .cross(world_triangle.v2 - world_triangle.v0)
+            .normalize();
+        let centroid = (world_triangle.v0 + world_triangle.v1 + world_triangle.v2) / 3.0;
+        let ray = Ray3d::new(centroid + expected_normal * 5.0, -expected_normal);
+
+        let handle = Handle::weak(HandleId::random::<Mesh>());
+        let mut bvh_cache = MeshBvhCache::default();
+        let hit = ray_mesh_intersection(
+            &handle,
+            &mesh_to_world,
+            &vertex_positions,
+            &ray,
+            &indices,
+            &RayCastSettings::default(),
+            &mut bvh_cache,
+        )
+        .expect("ray should hit the scaled triangle");
+
+        assert!(hit.normal().normalize().dot(expected_normal) > 0.99);
+    }
+}
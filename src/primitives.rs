@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+
+/// A 3D ray, with an origin and direction. The direction is not guaranteed to be normalized.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Ray3d {
+    origin: Vec3,
+    direction: Vec3,
+}
+
+impl Ray3d {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Ray3d { origin, direction }
+    }
+
+    /// Generate a ray from screen space coordinates, using the camera's projection and the
+    /// associated `GlobalTransform` to unproject the ray into world space.
+    pub fn from_screenspace(
+        cursor_pos_screen: Vec2,
+        windows: &Res<Windows>,
+        camera: &Camera,
+        camera_transform: &GlobalTransform,
+    ) -> Option<Self> {
+        let window = windows.get(camera.window)?;
+        let screen_size = Vec2::new(window.width(), window.height());
+        let cursor_ndc = (cursor_pos_screen / screen_size) * 2.0 - Vec2::ONE;
+        let camera_matrix = camera_transform.compute_matrix();
+        let (_, _, camera_position) = camera_matrix.to_scale_rotation_translation();
+
+        let ndc_to_world: Mat4 = camera_matrix * camera.projection_matrix.inverse();
+        let cursor_pos_near = ndc_to_world.project_point3(cursor_ndc.extend(-1.0));
+
+        let ray_direction = cursor_pos_near - camera_position;
+        Some(Ray3d::new(camera_position, ray_direction))
+    }
+
+    /// Create a ray from a transform, by transforming a ray pointing up from the origin.
+    pub fn from_transform(transform: Mat4) -> Self {
+        let source_origin = transform.project_point3(Vec3::ZERO);
+        let source_direction = transform.project_point3(Vec3::Y) - source_origin;
+        Ray3d::new(source_origin, source_direction)
+    }
+
+    pub fn origin(&self) -> Vec3 {
+        self.origin
+    }
+
+    pub fn direction(&self) -> Vec3 {
+        self.direction
+    }
+
+    pub fn position(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// A triangle, defined by three vertices in winding order.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Triangle {
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+}
+
+impl From<[Vec3; 3]> for Triangle {
+    fn from(vertices: [Vec3; 3]) -> Self {
+        Triangle {
+            v0: vertices[0],
+            v1: vertices[1],
+            v2: vertices[2],
+        }
+    }
+}
+
+/// The result of a successful ray cast: the time-of-impact `t` along the casting ray, the
+/// world-space point and surface normal at that point, the barycentric coordinates of the hit
+/// (when it came from a triangle test), whether the front or back face was hit, and the triangle
+/// that was hit, if any.
+///
+/// Barycentric coordinates let callers interpolate per-vertex attributes (UVs, vertex normals,
+/// vertex colors) at the hit point.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Intersection {
+    position: Vec3,
+    normal: Vec3,
+    distance: f32,
+    barycentric: Vec3,
+    front_face: bool,
+    triangle: Option<Triangle>,
+    triangle_index: Option<usize>,
+}
+
+impl Intersection {
+    pub fn new(
+        position: Vec3,
+        normal: Vec3,
+        distance: f32,
+        barycentric: Vec3,
+        front_face: bool,
+        triangle: Option<Triangle>,
+        triangle_index: Option<usize>,
+    ) -> Self {
+        Self {
+            position,
+            normal,
+            distance,
+            barycentric,
+            front_face,
+            triangle,
+            triangle_index,
+        }
+    }
+
+    /// The world-space point where the ray intersected the surface.
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    /// The surface normal at the point of intersection.
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// The distance along the ray, in units of the ray's direction length, at which the
+    /// intersection occurred. This is the same quantity commonly called `t`.
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// The barycentric coordinates `(u, v, w)` of the hit within [world_triangle], where
+    /// `w = 1.0 - u - v`. Only meaningful when [world_triangle] is `Some`.
+    ///
+    /// Despite the `(v0, v1, v2)` vertex order [world_triangle] uses, the weights are *not* in
+    /// that order: `u` is the weight of `v1`, `v` is the weight of `v2`, and `w` is the weight of
+    /// `v0`. To interpolate a per-vertex attribute `attr`, callers want
+    /// `attr[v0] * w + attr[v1] * u + attr[v2] * v`.
+    ///
+    /// [world_triangle]: Intersection::world_triangle
+    pub fn barycentric(&self) -> Vec3 {
+        self.barycentric
+    }
+
+    /// `true` if the ray hit the side of the surface its normal points towards (i.e. the normal
+    /// points back at the ray), `false` if it hit the surface from behind.
+    pub fn front_face(&self) -> bool {
+        self.front_face
+    }
+
+    /// The triangle that was hit, if the intersection came from a triangle test.
+    pub fn world_triangle(&self) -> Option<Triangle> {
+        self.triangle
+    }
+
+    /// The index of the triangle that was hit within whatever collection it was tested from (a
+    /// mesh's index buffer, or the slice passed to [cast_ray_triangles](crate::cast_ray_triangles)),
+    /// if the intersection came from a triangle test.
+    pub fn triangle_index(&self) -> Option<usize> {
+        self.triangle_index
+    }
+}
+
+/// Simple analytic shapes that can be ray cast against without needing a [Mesh].
+#[non_exhaustive]
+pub enum Primitive3d {
+    Plane { point: Vec3, normal: Vec3 },
+    Sphere { center: Vec3, radius: f32 },
+    Aabb { min: Vec3, max: Vec3 },
+    Triangle { v0: Vec3, v1: Vec3, v2: Vec3 },
+}
@@ -5,6 +5,7 @@ use bevy::prelude::*;
 
 #[allow(dead_code)]
 #[non_exhaustive]
+#[derive(Debug, Clone, Copy)]
 pub enum RaycastAlgorithm {
     Geometric,
     MollerTrumbore(Backfaces),
@@ -17,21 +18,26 @@ impl Default for RaycastAlgorithm {
 }
 
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
 pub enum Backfaces {
     Cull,
     Include,
 }
 
-/// Takes a ray and triangle and computes the intersection and normal
+/// Takes a ray and triangle and computes the intersection, time-of-impact, and barycentric
+/// coordinates of the hit, rejecting hits further than `max_toi` along the ray.
+///
+/// Passing `f32::MAX` for `max_toi` disables the cutoff.
 pub fn ray_triangle_intersection(
     ray: &Ray3d,
     triangle: &Triangle,
     algorithm: RaycastAlgorithm,
-) -> Option<Ray3d> {
+    max_toi: f32,
+) -> Option<Intersection> {
     match algorithm {
-        RaycastAlgorithm::Geometric => raycast_geometric(ray, triangle),
+        RaycastAlgorithm::Geometric => raycast_geometric(ray, triangle, max_toi),
         RaycastAlgorithm::MollerTrumbore(backface_culling) => {
-            raycast_moller_trumbore(ray, triangle, backface_culling)
+            raycast_moller_trumbore(ray, triangle, backface_culling, max_toi)
         }
     }
 }
@@ -41,7 +47,8 @@ pub fn raycast_moller_trumbore(
     ray: &Ray3d,
     triangle: &Triangle,
     backface_culling: Backfaces,
-) -> Option<Ray3d> {
+    max_toi: f32,
+) -> Option<Intersection> {
     // Source: https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/moller-trumbore-ray-triangle-intersection
     let vector_v0_to_v1: Vec3 = triangle.v1 - triangle.v0;
     let vector_v0_to_v2: Vec3 = triangle.v2 - triangle.v0;
@@ -81,16 +88,30 @@ pub fn raycast_moller_trumbore(
 
     // The distance between ray origin and intersection is t.
     let t: f32 = vector_v0_to_v2.dot(q_vec) * determinant_inverse;
+    if t < 0.0 || t > max_toi {
+        return None;
+    }
 
     // Move along the ray direction from the origin, to find the intersection
     let point_intersection = ray.origin() + ray.direction() * t;
     let triangle_normal = vector_v0_to_v1.cross(vector_v0_to_v2);
+    let w = 1.0 - u - v;
+    // The determinant is positive iff the ray hits the side the normal points towards.
+    let front_face = determinant > 0.0;
 
-    Some(Ray3d::new(point_intersection, triangle_normal))
+    Some(Intersection::new(
+        point_intersection,
+        triangle_normal.normalize(),
+        t,
+        Vec3::new(u, v, w),
+        front_face,
+        Some(*triangle),
+        None,
+    ))
 }
 
 /// Geometric method of computing a ray-triangle intersection
-pub fn raycast_geometric(ray: &Ray3d, triangle: &Triangle) -> Option<Ray3d> {
+pub fn raycast_geometric(ray: &Ray3d, triangle: &Triangle, max_toi: f32) -> Option<Intersection> {
     // Source: https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/ray-triangle-intersection-geometric-solution
     // compute plane's normal
     let vector_v0_to_v1: Vec3 = triangle.v1 - triangle.v0;
@@ -111,10 +132,10 @@ pub fn raycast_geometric(ray: &Ray3d, triangle: &Triangle) -> Option<Ray3d> {
 
     // compute t (equation 3)
     let t = (triangle_normal.dot(ray.origin()) + d) / n_dot_ray_direction;
-    // check if the triangle is in behind the ray
-    if t < 0.0 {
+    // check if the triangle is behind the ray, or farther than the caller wants to know about
+    if t < 0.0 || t > max_toi {
         return None;
-    } // the triangle is behind
+    }
 
     // compute the intersection point using equation 1
     let point_intersection = ray.origin() + t * ray.direction();
@@ -124,28 +145,94 @@ pub fn raycast_geometric(ray: &Ray3d, triangle: &Triangle) -> Option<Ray3d> {
     // edge 0
     let edge0 = triangle.v1 - triangle.v0;
     let vp0 = point_intersection - triangle.v0;
-    let cross = edge0.cross(vp0);
-    if triangle_normal.dot(cross) < 0.0 {
+    let cross0 = edge0.cross(vp0);
+    if triangle_normal.dot(cross0) < 0.0 {
         return None;
     } // P is on the right side
 
     // edge 1
     let edge1 = triangle.v2 - triangle.v1;
     let vp1 = point_intersection - triangle.v1;
-    let cross = edge1.cross(vp1);
-    if triangle_normal.dot(cross) < 0.0 {
+    let cross1 = edge1.cross(vp1);
+    if triangle_normal.dot(cross1) < 0.0 {
         return None;
     } // P is on the right side
 
     // edge 2
     let edge2 = triangle.v0 - triangle.v2;
     let vp2 = point_intersection - triangle.v2;
-    let cross = edge2.cross(vp2);
-    if triangle_normal.dot(cross) < 0.0 {
+    let cross2 = edge2.cross(vp2);
+    if triangle_normal.dot(cross2) < 0.0 {
         return None;
     } // P is on the right side;
 
-    Some(Ray3d::new(point_intersection, triangle_normal))
+    // Barycentric coordinates, derived from the same edge-function areas used by the
+    // inside-outside test above, to match the (u, v, w) convention used by the
+    // Möller-Trumbore path: u is the v1 weight, v is the v2 weight, w is the v0 weight.
+    let normal_length_squared = triangle_normal.length_squared();
+    let v = triangle_normal.dot(cross0) / normal_length_squared;
+    let w = triangle_normal.dot(cross1) / normal_length_squared;
+    let u = 1.0 - v - w;
+    // The ray hits the front face when it travels against the triangle's normal.
+    let front_face = n_dot_ray_direction < 0.0;
+
+    Some(Intersection::new(
+        point_intersection,
+        triangle_normal.normalize(),
+        t,
+        Vec3::new(u, v, w),
+        front_face,
+        Some(*triangle),
+        None,
+    ))
+}
+
+/// Casts a ray against a slice of triangles, returning every hit paired with the index of the
+/// triangle it came from, sorted by ascending time-of-impact (nearest first).
+pub fn cast_ray_triangles(
+    ray: &Ray3d,
+    triangles: &[Triangle],
+    algorithm: RaycastAlgorithm,
+    max_toi: f32,
+) -> Vec<(usize, Intersection)> {
+    let mut hits: Vec<(usize, Intersection)> = triangles
+        .iter()
+        .enumerate()
+        .filter_map(|(index, triangle)| {
+            ray_triangle_intersection(ray, triangle, algorithm, max_toi).map(|intersection| {
+                (
+                    index,
+                    Intersection::new(
+                        intersection.position(),
+                        intersection.normal(),
+                        intersection.distance(),
+                        intersection.barycentric(),
+                        intersection.front_face(),
+                        intersection.world_triangle(),
+                        Some(index),
+                    ),
+                )
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| {
+        a.1.distance()
+            .partial_cmp(&b.1.distance())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    hits
+}
+
+/// Casts a ray against a slice of triangles and returns only the nearest hit, if any.
+pub fn nearest_ray_triangle_intersection(
+    ray: &Ray3d,
+    triangles: &[Triangle],
+    algorithm: RaycastAlgorithm,
+    max_toi: f32,
+) -> Option<(usize, Intersection)> {
+    cast_ray_triangles(ray, triangles, algorithm, max_toi)
+        .into_iter()
+        .next()
 }
 
 #[cfg(test)]
@@ -162,11 +249,10 @@ mod tests {
         let triangle = Triangle::from([V0.into(), V1.into(), V2.into()]);
         let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
         let algorithm = RaycastAlgorithm::MollerTrumbore(Backfaces::Include);
-        let result = ray_triangle_intersection(&ray, &triangle, algorithm);
-        assert_eq!(
-            result,
-            Some(Ray3d::new([1.0, 0.0, 0.0].into(), [-1.0, 0.0, 0.0].into()))
-        );
+        let result = ray_triangle_intersection(&ray, &triangle, algorithm, f32::MAX).unwrap();
+        assert_eq!(result.position(), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(result.normal(), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(result.distance(), 1.0);
     }
 
     #[test]
@@ -174,7 +260,7 @@ mod tests {
         let triangle = Triangle::from([V2.into(), V1.into(), V0.into()]);
         let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
         let algorithm = RaycastAlgorithm::MollerTrumbore(Backfaces::Cull);
-        let result = ray_triangle_intersection(&ray, &triangle, algorithm);
+        let result = ray_triangle_intersection(&ray, &triangle, algorithm, f32::MAX);
         assert_eq!(result, None);
     }
 
@@ -183,10 +269,75 @@ mod tests {
         let triangle = Triangle::from([V0.into(), V1.into(), V2.into()]);
         let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
         let algorithm = RaycastAlgorithm::Geometric;
-        let result = ray_triangle_intersection(&ray, &triangle, algorithm);
-        assert_eq!(
-            result,
-            Some(Ray3d::new([1.0, 0.0, 0.0].into(), [-1.0, 0.0, 0.0].into()))
+        let result = ray_triangle_intersection(&ray, &triangle, algorithm, f32::MAX).unwrap();
+        assert_eq!(result.position(), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(result.normal(), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(result.distance(), 1.0);
+    }
+
+    #[test]
+    fn raycast_triangle_barycentric_sums_to_one() {
+        let triangle = Triangle::from([V0.into(), V1.into(), V2.into()]);
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let result = ray_triangle_intersection(
+            &ray,
+            &triangle,
+            RaycastAlgorithm::MollerTrumbore(Backfaces::Include),
+            f32::MAX,
+        )
+        .unwrap();
+        let barycentric = result.barycentric();
+        assert!((barycentric.x + barycentric.y + barycentric.z - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn cast_ray_triangles_sorts_by_distance() {
+        let near = Triangle::from([V0.into(), V1.into(), V2.into()]);
+        let far = Triangle::from([
+            Vec3::from(V0) + Vec3::X * 5.0,
+            Vec3::from(V1) + Vec3::X * 5.0,
+            Vec3::from(V2) + Vec3::X * 5.0,
+        ]);
+        let triangles = [far, near];
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let hits = cast_ray_triangles(
+            &ray,
+            &triangles,
+            RaycastAlgorithm::MollerTrumbore(Backfaces::Include),
+            f32::MAX,
         );
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].0, 1); // the `near` triangle, despite being listed second
+        assert!(hits[0].1.distance() < hits[1].1.distance());
+    }
+
+    #[test]
+    fn nearest_ray_triangle_intersection_returns_closest() {
+        let near = Triangle::from([V0.into(), V1.into(), V2.into()]);
+        let far = Triangle::from([
+            Vec3::from(V0) + Vec3::X * 5.0,
+            Vec3::from(V1) + Vec3::X * 5.0,
+            Vec3::from(V2) + Vec3::X * 5.0,
+        ]);
+        let triangles = [near, far];
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let (index, _) = nearest_ray_triangle_intersection(
+            &ray,
+            &triangles,
+            RaycastAlgorithm::MollerTrumbore(Backfaces::Include),
+            f32::MAX,
+        )
+        .unwrap();
+        assert_eq!(index, 0);
+    }
+
+    #[test]
+    fn raycast_triangle_mt_respects_max_toi() {
+        let triangle = Triangle::from([V0.into(), V1.into(), V2.into()]);
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let algorithm = RaycastAlgorithm::MollerTrumbore(Backfaces::Include);
+        // The triangle is hit at t == 1.0, so a tighter cutoff should reject it.
+        let result = ray_triangle_intersection(&ray, &triangle, algorithm, 0.5);
+        assert_eq!(result, None);
     }
 }
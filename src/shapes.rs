@@ -0,0 +1,278 @@
+use std::f32::EPSILON;
+
+use bevy::prelude::*;
+
+use crate::primitives::*;
+
+/// A sphere, defined by a world-space center and radius.
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+/// An axis-aligned bounding box, defined by its `min` and `max` corners in world space.
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+/// An oriented box, defined by a `transform` (translation, rotation, and scale) applied to a unit
+/// box with the given `half_extents`.
+pub struct Cuboid {
+    pub transform: Mat4,
+    pub half_extents: Vec3,
+}
+
+/// A finite cylinder, defined by a `transform` whose local Y axis is the cylinder's axis, a
+/// `radius`, and a `half_height` along that axis.
+pub struct Cylinder {
+    pub transform: Mat4,
+    pub radius: f32,
+    pub half_height: f32,
+}
+
+/// Ray-plane intersection. Solves `t = dot(normal, point - origin) / dot(normal, direction)`,
+/// rejecting rays parallel to the plane or intersections behind the ray origin.
+pub fn ray_plane_intersection(ray: &Ray3d, point: Vec3, normal: Vec3) -> Option<Intersection> {
+    let denominator = ray.direction().dot(normal);
+    if denominator.abs() < EPSILON {
+        return None;
+    }
+    let t = normal.dot(point - ray.origin()) / denominator;
+    if t < 0.0 {
+        return None;
+    }
+    let position = ray.position(t);
+    let front_face = ray.direction().dot(normal) < 0.0;
+    Some(Intersection::new(position, normal, t, Vec3::ZERO, front_face, None, None))
+}
+
+/// Ray-sphere intersection. Solves the quadratic `|origin + t*dir - center|^2 = r^2` and returns
+/// the nearest non-negative root.
+pub fn ray_sphere_intersection(ray: &Ray3d, sphere: &Sphere) -> Option<Intersection> {
+    let direction = ray.direction();
+    let oc = ray.origin() - sphere.center;
+    let a = direction.length_squared();
+    let b = 2.0 * oc.dot(direction);
+    let c = oc.length_squared() - sphere.radius * sphere.radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrt_discriminant = discriminant.sqrt();
+    let t0 = (-b - sqrt_discriminant) / (2.0 * a);
+    let t1 = (-b + sqrt_discriminant) / (2.0 * a);
+    let t = if t0 >= 0.0 {
+        t0
+    } else if t1 >= 0.0 {
+        t1
+    } else {
+        return None;
+    };
+    let position = ray.position(t);
+    let normal = (position - sphere.center) / sphere.radius;
+    let front_face = ray.direction().dot(normal) < 0.0;
+    Some(Intersection::new(position, normal, t, Vec3::ZERO, front_face, None, None))
+}
+
+/// Ray-AABB intersection via the slab method, returning the entry `t` and the normal of whichever
+/// face was entered through.
+pub fn ray_aabb_intersection(ray: &Ray3d, aabb: &Aabb) -> Option<Intersection> {
+    let origin = ray.origin();
+    let inv_direction = ray.direction().recip();
+
+    let mut tmin = f32::MIN;
+    let mut tmax = f32::MAX;
+    let mut normal = Vec3::ZERO;
+
+    // For each axis, compute the near/far slab crossing distances and narrow [tmin, tmax],
+    // remembering which axis (and which of its two faces) most recently pushed tmin inward.
+    let axes = [
+        (aabb.min.x, aabb.max.x, origin.x, inv_direction.x, Vec3::X),
+        (aabb.min.y, aabb.max.y, origin.y, inv_direction.y, Vec3::Y),
+        (aabb.min.z, aabb.max.z, origin.z, inv_direction.z, Vec3::Z),
+    ];
+    for (min, max, origin_axis, inv_dir_axis, axis_normal) in axes {
+        let mut t_near = (min - origin_axis) * inv_dir_axis;
+        let mut t_far = (max - origin_axis) * inv_dir_axis;
+        let mut entry_normal = -axis_normal;
+        if t_near > t_far {
+            std::mem::swap(&mut t_near, &mut t_far);
+            entry_normal = axis_normal;
+        }
+        if t_near > tmin {
+            tmin = t_near;
+            normal = entry_normal;
+        }
+        tmax = tmax.min(t_far);
+        if tmin > tmax {
+            return None;
+        }
+    }
+
+    let t = tmin.max(0.0);
+    if tmax < t {
+        return None;
+    }
+    let position = ray.position(t);
+    let front_face = ray.direction().dot(normal) < 0.0;
+    Some(Intersection::new(position, normal, t, Vec3::ZERO, front_face, None, None))
+}
+
+/// Ray-cuboid intersection. Transforms the ray into the cuboid's local space, reuses the AABB
+/// slab test against `[-half_extents, half_extents]`, then transforms the hit back into world
+/// space.
+pub fn ray_cuboid_intersection(ray: &Ray3d, cuboid: &Cuboid) -> Option<Intersection> {
+    let local_to_world = cuboid.transform;
+    let world_to_local = local_to_world.inverse();
+    let local_ray = Ray3d::new(
+        world_to_local.transform_point3(ray.origin()),
+        world_to_local.transform_vector3(ray.direction()),
+    );
+    let local_aabb = Aabb {
+        min: -cuboid.half_extents,
+        max: cuboid.half_extents,
+    };
+    let local_hit = ray_aabb_intersection(&local_ray, &local_aabb)?;
+
+    let position = local_to_world.transform_point3(local_hit.position());
+    let normal = local_to_world
+        .transform_vector3(local_hit.normal())
+        .normalize();
+    let distance = (position - ray.origin()).length() / ray.direction().length();
+    let front_face = ray.direction().dot(normal) < 0.0;
+    Some(Intersection::new(
+        position,
+        normal,
+        distance,
+        Vec3::ZERO,
+        front_face,
+        None,
+        None,
+    ))
+}
+
+/// Ray-cylinder intersection against a finite cylinder whose axis is the local Y axis. Solves the
+/// quadratic for the infinite cylinder's side, then clamps against the two end caps.
+pub fn ray_cylinder_intersection(ray: &Ray3d, cylinder: &Cylinder) -> Option<Intersection> {
+    let local_to_world = cylinder.transform;
+    let world_to_local = local_to_world.inverse();
+    let origin = world_to_local.transform_point3(ray.origin());
+    let direction = world_to_local.transform_vector3(ray.direction());
+
+    let mut best: Option<(f32, Vec3)> = None;
+    let mut consider = |t: f32, local_normal: Vec3| {
+        if t < 0.0 {
+            return;
+        }
+        if best.map_or(true, |(best_t, _)| t < best_t) {
+            best = Some((t, local_normal));
+        }
+    };
+
+    // Side of the cylinder: solve the infinite-cylinder quadratic in x/z.
+    let a = direction.x * direction.x + direction.z * direction.z;
+    if a > EPSILON {
+        let b = 2.0 * (origin.x * direction.x + origin.z * direction.z);
+        let c = origin.x * origin.x + origin.z * origin.z - cylinder.radius * cylinder.radius;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant >= 0.0 {
+            let sqrt_discriminant = discriminant.sqrt();
+            for t in [
+                (-b - sqrt_discriminant) / (2.0 * a),
+                (-b + sqrt_discriminant) / (2.0 * a),
+            ] {
+                let y = origin.y + t * direction.y;
+                if y.abs() <= cylinder.half_height {
+                    let hit = origin + direction * t;
+                    consider(t, Vec3::new(hit.x, 0.0, hit.z).normalize_or_zero());
+                }
+            }
+        }
+    }
+
+    // End caps.
+    for cap_y in [-cylinder.half_height, cylinder.half_height] {
+        if direction.y.abs() < EPSILON {
+            continue;
+        }
+        let t = (cap_y - origin.y) / direction.y;
+        let hit = origin + direction * t;
+        if hit.x * hit.x + hit.z * hit.z <= cylinder.radius * cylinder.radius {
+            consider(t, Vec3::new(0.0, cap_y.signum(), 0.0));
+        }
+    }
+
+    let (t, local_normal) = best?;
+    let local_position = origin + direction * t;
+    let position = local_to_world.transform_point3(local_position);
+    let normal = local_to_world.transform_vector3(local_normal).normalize();
+    let distance = (position - ray.origin()).length() / ray.direction().length();
+    let front_face = ray.direction().dot(normal) < 0.0;
+    Some(Intersection::new(
+        position,
+        normal,
+        distance,
+        Vec3::ZERO,
+        front_face,
+        None,
+        None,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sphere_hit_from_outside() {
+        let sphere = Sphere {
+            center: Vec3::new(5.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let hit = ray_sphere_intersection(&ray, &sphere).unwrap();
+        assert_eq!(hit.position(), Vec3::new(4.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn sphere_miss() {
+        let sphere = Sphere {
+            center: Vec3::new(5.0, 5.0, 0.0),
+            radius: 1.0,
+        };
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        assert!(ray_sphere_intersection(&ray, &sphere).is_none());
+    }
+
+    #[test]
+    fn aabb_hit_from_outside() {
+        let aabb = Aabb {
+            min: Vec3::new(4.0, -1.0, -1.0),
+            max: Vec3::new(6.0, 1.0, 1.0),
+        };
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let hit = ray_aabb_intersection(&ray, &aabb).unwrap();
+        assert_eq!(hit.position(), Vec3::new(4.0, 0.0, 0.0));
+        assert_eq!(hit.normal(), Vec3::new(-1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn plane_hit() {
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::X);
+        let hit = ray_plane_intersection(&ray, Vec3::new(4.0, 0.0, 0.0), Vec3::NEG_X).unwrap();
+        assert_eq!(hit.position(), Vec3::new(4.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn cylinder_hit_from_side() {
+        let cylinder = Cylinder {
+            transform: Mat4::IDENTITY,
+            radius: 1.0,
+            half_height: 2.0,
+        };
+        let ray = Ray3d::new(Vec3::new(5.0, 0.0, 0.0), Vec3::NEG_X);
+        let hit = ray_cylinder_intersection(&ray, &cylinder).unwrap();
+        assert_eq!(hit.position(), Vec3::new(1.0, 0.0, 0.0));
+    }
+}